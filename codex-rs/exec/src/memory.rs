@@ -1,18 +1,122 @@
 use chrono::Utc;
+use regex_lite::Regex;
+use serde::Deserialize;
 use serde_json::json;
-use std::fs::{create_dir_all, OpenOptions};
+use std::fs::OpenOptions;
+use std::fs::create_dir_all;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
 use std::time::Duration;
 use uuid::Uuid;
+use wildmatch::WildMatch;
 
-/// Minimal per-repo memory logger that writes JSONL entries to
-/// `<repo>/.codex/memory/memory.jsonl`.
+/// Entry `type`s written by the logger. These are high-volume activity records
+/// and live in `events.jsonl`, separate from durable items in `memory.jsonl`.
+const EVENT_TYPES: &[&str] = &["exec", "tool", "change"];
+
+/// Minimal per-repo memory logger that writes JSONL event entries to
+/// `<repo>/.codex/memory/events.jsonl`.
 pub(crate) struct MemoryLogger {
     repo_root: PathBuf,
+    /// `repo_root` plus, when different, the same directory as spelled by the
+    /// uncanonicalized start path. Paths reported by codex are built from the
+    /// latter, so both are needed to make them repo-relative.
+    root_aliases: Vec<PathBuf>,
     memory_dir: PathBuf,
-    memory_file: PathBuf,
+    events_file: PathBuf,
     index_file: PathBuf,
+    ignore: Vec<IgnoreRule>,
+    config: MemoryConfig,
+}
+
+/// Per-repo memory settings read from `.codex/memory/config.toml`. Missing
+/// keys keep the built-in defaults.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+struct MemoryConfig {
+    /// Whether exec/tool/change events are written to `events.jsonl`.
+    events_enabled: bool,
+    /// Size at which `events.jsonl` is renamed to
+    /// `events-<timestamp>-<n>.jsonl` and a fresh file started.
+    events_max_bytes: u64,
+    /// Number of rotated event logs retained; older ones are deleted.
+    events_keep: usize,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            events_enabled: true,
+            events_max_bytes: 10 * 1024 * 1024,
+            events_keep: 5,
+        }
+    }
+}
+
+impl MemoryConfig {
+    fn load(config_file: &Path) -> Self {
+        let Ok(text) = std::fs::read_to_string(config_file) else {
+            return Self::default();
+        };
+        toml::from_str(&text).unwrap_or_else(|e| {
+            tracing::debug!("memory: invalid config.toml: {e}");
+            Self::default()
+        })
+    }
+}
+
+/// A rule from `.codex/memory/ignore`. Entries matching any rule are dropped
+/// before they are written, unlike redaction which only masks values.
+#[derive(Debug)]
+enum IgnoreRule {
+    /// `re:<pattern>` lines, searched within the content and file paths.
+    Regex(Regex),
+    /// Any other line, matched as a glob against the content and file paths.
+    Glob(WildMatch),
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        if let Some(pattern) = line.strip_prefix("re:") {
+            match Regex::new(pattern) {
+                Ok(re) => Some(Self::Regex(re)),
+                Err(e) => {
+                    tracing::debug!("memory: invalid ignore regex {pattern:?}: {e}");
+                    None
+                }
+            }
+        } else {
+            Some(Self::Glob(WildMatch::new(line)))
+        }
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Regex(re) => re.is_match(text),
+            Self::Glob(glob) => glob.matches(text),
+        }
+    }
+}
+
+/// Entry fields written by the logger itself, never matched against ignore
+/// rules (otherwise e.g. a regex hitting the repo path would drop everything).
+const IGNORE_EXEMPT_KEYS: &[&str] = &["id", "ts", "repo", "type", "session_id", "source"];
+
+fn load_ignore_rules(path: &Path) -> Vec<IgnoreRule> {
+    std::fs::read_to_string(path)
+        .map(|s| s.lines().filter_map(IgnoreRule::parse).collect())
+        .unwrap_or_default()
 }
 
 #[derive(Debug, Clone)]
@@ -27,45 +131,142 @@ pub(crate) struct ToolInvocation {
 
 impl MemoryLogger {
     pub fn new(start_path: PathBuf) -> Self {
-        let repo_root = detect_repo_root(&start_path).unwrap_or(start_path);
+        let repo_root = detect_repo_root(&start_path, &RepoRootOptions::from_env())
+            .unwrap_or_else(|| start_path.clone());
+        let mut root_aliases = vec![repo_root.clone()];
+        if let Some(original) = start_path
+            .ancestors()
+            .find(|a| a.canonicalize().is_ok_and(|c| c == repo_root))
+            && original != repo_root
+        {
+            root_aliases.push(original.to_path_buf());
+        }
         let memory_dir = repo_root.join(".codex").join("memory");
-        let memory_file = memory_dir.join("memory.jsonl");
+        let events_file = memory_dir.join("events.jsonl");
         let index_file = memory_dir.join("index.json");
+        let ignore = load_ignore_rules(&memory_dir.join("ignore"));
+        let config = MemoryConfig::load(&memory_dir.join("config.toml"));
         // Best-effort create, ignore errors here; we'll handle on write.
         let _ = create_dir_all(&memory_dir);
+        // Older versions appended events to `memory.jsonl`; move them out.
+        match migrate_event_lines(&memory_dir) {
+            Ok(0) => {}
+            Ok(n) => tracing::debug!("memory: moved {n} event lines to events.jsonl"),
+            Err(e) => tracing::debug!("memory: event migration failed: {e}"),
+        }
         Self {
             repo_root,
+            root_aliases,
             memory_dir,
-            memory_file,
+            events_file,
             index_file,
+            ignore,
+            config,
+        }
+    }
+
+    fn rotate_if_needed(&self) -> std::io::Result<()> {
+        let len = match std::fs::metadata(&self.events_file) {
+            Ok(meta) => meta.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if len < self.config.events_max_bytes.max(1) {
+            return Ok(());
+        }
+
+        // The zero-padded counter disambiguates rotations within the same
+        // millisecond while keeping names in chronological sort order.
+        let stamp = Utc::now().format(ROTATED_STAMP_FORMAT);
+        let mut n = 0;
+        let mut rotated = self.memory_dir.join(format!("events-{stamp}-{n:03}.jsonl"));
+        while rotated.exists() {
+            n += 1;
+            rotated = self.memory_dir.join(format!("events-{stamp}-{n:03}.jsonl"));
+        }
+        std::fs::rename(&self.events_file, &rotated)?;
+
+        let mut old = rotated_event_logs(&self.memory_dir)?;
+        let excess = old.len().saturating_sub(self.config.events_keep);
+        for path in old.drain(..excess) {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Whether any ignore rule matches a string anywhere in the entry (content,
+    /// files, hints, output previews, tool results), skipping only the
+    /// bookkeeping fields. Absolute paths are also tried repo-relative so globs
+    /// like `secrets/*` apply to the absolute paths codex reports.
+    fn is_ignored(&self, value: &serde_json::Value) -> bool {
+        fn walk(value: &serde_json::Value, f: &mut dyn FnMut(&str) -> bool) -> bool {
+            match value {
+                serde_json::Value::String(s) => f(s),
+                serde_json::Value::Array(items) => items.iter().any(|v| walk(v, f)),
+                serde_json::Value::Object(map) => map.values().any(|v| walk(v, f)),
+                _ => false,
+            }
         }
+
+        if self.ignore.is_empty() {
+            return false;
+        }
+        let mut matches = |text: &str| self.matches_ignore_rule(text);
+        let Some(entry) = value.as_object() else {
+            return walk(value, &mut matches);
+        };
+        entry
+            .iter()
+            .filter(|(key, _)| !IGNORE_EXEMPT_KEYS.contains(&key.as_str()))
+            .any(|(_, v)| walk(v, &mut matches))
+    }
+
+    /// Tests `text` and, for paths under the repo, its repo-relative form.
+    fn matches_ignore_rule(&self, text: &str) -> bool {
+        let rel = repo_relative(text, &self.root_aliases)
+            .or_else(|| text.strip_prefix("./").map(str::to_string));
+        std::iter::once(text)
+            .chain(rel.as_deref())
+            .any(|t| self.ignore.iter().any(|rule| rule.matches(t)))
+    }
+
+    /// Whether any shell word of an exec command matches an ignore rule, so
+    /// path globs fire for files that never become file hints (e.g.
+    /// extensionless `secrets/prod_token`).
+    fn is_command_ignored(&self, command: &[String]) -> bool {
+        !self.ignore.is_empty()
+            && command_tokens(command)
+                .iter()
+                .any(|token| self.matches_ignore_rule(token))
     }
 
     fn write_line(&self, value: &serde_json::Value) {
+        if !self.config.events_enabled || self.is_ignored(value) {
+            return;
+        }
         if let Err(e) = create_dir_all(&self.memory_dir) {
             tracing::debug!("memory: create_dir_all failed: {e}");
             return;
         }
-        match OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.memory_file)
-        {
-            Ok(mut f) => {
-                if let Ok(s) = serde_json::to_string(value) {
-                    let _ = writeln!(f, "{}", s);
-                }
-            }
-            Err(e) => {
-                tracing::debug!("memory: open append failed: {e}");
-            }
+        if let Err(e) = self.rotate_if_needed() {
+            tracing::debug!("memory: event log rotation failed: {e}");
+        }
+        let Ok(line) = serde_json::to_string(value) else {
+            return;
+        };
+        if let Err(e) = append_lines(&self.events_file, &[line]) {
+            tracing::debug!("memory: append failed: {e}");
         }
     }
 
     pub fn log_exec(&self, command: &[String], exit_code: i32, duration: Duration, output: &str) {
+        if self.is_command_ignored(command) {
+            return;
+        }
         let id = Uuid::new_v4().to_string();
         let ts = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
         let preview = truncate_multiline(output, 160, 20);
+        let hints = RelevanceHints::from_command(command);
         let value = json!({
             "id": id,
             "ts": ts,
@@ -73,7 +274,8 @@ impl MemoryLogger {
             "type": "exec",
             "content": format!("{}", shlex::try_join(command.iter().map(|s| s.as_str())).unwrap_or_else(|_| command.join(" "))),
             "tags": ["exec"],
-            "files": [],
+            "files": hints.files,
+            "hints": hints.to_json(),
             "session_id": null,
             "source": "codex-rs",
             "metadata": {
@@ -98,6 +300,11 @@ impl MemoryLogger {
         } else {
             format!("{}.{}({})", inv.server, inv.tool, args_str)
         };
+        let hints = inv
+            .arguments
+            .as_ref()
+            .map(RelevanceHints::from_tool_arguments)
+            .unwrap_or_default();
         let value = json!({
             "id": id,
             "ts": ts,
@@ -105,7 +312,8 @@ impl MemoryLogger {
             "type": "tool",
             "content": content,
             "tags": ["tool"],
-            "files": [],
+            "files": hints.files,
+            "hints": hints.to_json(),
             "session_id": null,
             "source": "codex-rs",
             "metadata": {
@@ -131,6 +339,7 @@ impl MemoryLogger {
         let id = Uuid::new_v4().to_string();
         let ts = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
         let preview = if success { stdout } else { stderr };
+        let hints = RelevanceHints::from_changed_files(files, &self.root_aliases);
         let value = json!({
             "id": id,
             "ts": ts,
@@ -138,7 +347,8 @@ impl MemoryLogger {
             "type": "change",
             "content": format!("apply_patch(auto_approved={})", auto_approved),
             "tags": ["apply_patch"],
-            "files": files,
+            "files": hints.files,
+            "hints": hints.to_json(),
             "session_id": null,
             "source": "codex-rs",
             "metadata": {
@@ -152,6 +362,240 @@ impl MemoryLogger {
     }
 }
 
+/// Recall hints derived from a logged entry, mirroring the `files`,
+/// `commands` and `languages` lists of the typed memory item's relevance hints.
+#[derive(Debug, Default, PartialEq)]
+struct RelevanceHints {
+    files: Vec<String>,
+    commands: Vec<String>,
+    languages: Vec<String>,
+}
+
+impl RelevanceHints {
+    /// Extracts the program names and path-like arguments from an exec
+    /// command. `bash -lc "<script>"` style wrappers are unwrapped so the
+    /// hints reflect the script rather than the shell.
+    fn from_command(command: &[String]) -> Self {
+        let mut hints = Self::default();
+        let mut expect_program = true;
+        let mut skip_next = false;
+        for token in &command_tokens(command) {
+            if matches!(token.as_str(), "&&" | "||" | "|" | ";") {
+                expect_program = true;
+            } else if expect_program {
+                // `RUST_LOG=debug cargo test`: assignments precede the program.
+                if is_env_assignment(token) {
+                    continue;
+                }
+                let name = Path::new(token)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| token.clone());
+                push_unique(&mut hints.commands, name);
+                expect_program = false;
+            } else if std::mem::take(&mut skip_next) {
+                // Value of `-m`/`-c`: a message, module or inline script.
+            } else if matches!(token.as_str(), "-m" | "-c") {
+                skip_next = true;
+            } else if looks_like_path(token) {
+                push_unique(&mut hints.files, token.clone());
+            }
+        }
+        hints.with_languages()
+    }
+
+    /// Collects string values stored under the [`TOOL_PATH_KEYS`] of a tool
+    /// call's arguments.
+    fn from_tool_arguments(arguments: &serde_json::Value) -> Self {
+        fn walk(value: &serde_json::Value, path_key: bool, files: &mut Vec<String>) {
+            match value {
+                serde_json::Value::String(s) if path_key && is_path_candidate(s) => {
+                    push_unique(files, s.clone());
+                }
+                serde_json::Value::Array(items) => {
+                    for item in items {
+                        walk(item, path_key, files);
+                    }
+                }
+                serde_json::Value::Object(map) => {
+                    for (key, item) in map {
+                        let key = key.to_ascii_lowercase();
+                        walk(item, TOOL_PATH_KEYS.contains(&key.as_str()), files);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut hints = Self::default();
+        walk(arguments, false, &mut hints.files);
+        hints.with_languages()
+    }
+
+    /// Uses the files touched by a patch, made relative to the repo root where
+    /// possible so they match hints recorded from commands run in the repo.
+    fn from_changed_files(files: &[String], root_aliases: &[PathBuf]) -> Self {
+        let mut hints = Self::default();
+        for file in files {
+            let rel = repo_relative(file, root_aliases).unwrap_or_else(|| file.clone());
+            push_unique(&mut hints.files, rel);
+        }
+        hints.with_languages()
+    }
+
+    /// Fills `languages` from the extensions of `files`.
+    fn with_languages(mut self) -> Self {
+        for file in &self.files {
+            if let Some(lang) = language_for_path(Path::new(file)) {
+                push_unique(&mut self.languages, lang.to_string());
+            }
+        }
+        self
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "files": self.files,
+            "commands": self.commands,
+            "languages": self.languages,
+        })
+    }
+}
+
+/// Strips the first matching root in `root_aliases` from `path`.
+fn repo_relative(path: &str, root_aliases: &[PathBuf]) -> Option<String> {
+    root_aliases
+        .iter()
+        .find_map(|root| Path::new(path).strip_prefix(root).ok())
+        .map(|p| p.to_string_lossy().to_string())
+}
+
+/// Maps a file extension to the language name used for recall hints.
+fn language_for_path(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    let lang = match ext.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "ts" | "tsx" | "mts" | "cts" => "typescript",
+        "js" | "jsx" | "mjs" | "cjs" => "javascript",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "sh" | "bash" | "zsh" => "shell",
+        "toml" => "toml",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "md" => "markdown",
+        _ => return None,
+    };
+    Some(lang)
+}
+
+fn is_shell(program: &str) -> bool {
+    matches!(
+        Path::new(program).file_name().and_then(|n| n.to_str()),
+        Some("bash" | "sh" | "zsh")
+    )
+}
+
+/// Tool argument keys whose string values name files. Anything else (e.g.
+/// `profile`, `file_text`) is free-form and never recorded as a file.
+const TOOL_PATH_KEYS: &[&str] = &["path", "paths", "file", "files", "file_path", "filename"];
+
+/// Rejects tokens that cannot be file paths: flags, assignments, globs, URLs,
+/// sed expressions, anything containing whitespace, directory-only tokens
+/// ending in `/`, and tokens with no named segment (`.`, `../..`).
+fn is_path_candidate(token: &str) -> bool {
+    if token.is_empty()
+        || token.starts_with('-')
+        || token.contains(char::is_whitespace)
+        || token.ends_with('/')
+        || token.contains(['=', '*', '?', '['])
+        || token.contains("://")
+        || is_sed_expression(token)
+    {
+        return false;
+    }
+    Path::new(token)
+        .components()
+        .any(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Splits an exec command into shell words. `bash -lc "<script>"` style
+/// wrappers are unwrapped so the words come from the script, not the shell.
+fn command_tokens(command: &[String]) -> Vec<String> {
+    match command {
+        [shell, flag, script] if is_shell(shell) && matches!(flag.as_str(), "-c" | "-lc") => {
+            shlex::split(script).unwrap_or_else(|| vec![script.clone()])
+        }
+        _ => command.to_vec(),
+    }
+}
+
+fn is_env_assignment(token: &str) -> bool {
+    token.split_once('=').is_some_and(|(name, _)| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Extensions of non-source files commonly named on command lines, in
+/// addition to those known to [`language_for_path`].
+const PLAIN_FILE_EXTENSIONS: &[&str] = &[
+    "txt", "log", "lock", "csv", "xml", "html", "css", "ini", "cfg", "conf", "env", "pem", "sql",
+    "patch", "diff",
+];
+
+/// Heuristic for free-form command arguments that name files. Beyond
+/// [`is_path_candidate`], the token needs an explicit `/`, `./`, `../` or
+/// `~/` prefix, or an extension. Bare names (no `/`) need a known file
+/// extension so that hosts (`example.com`) and modules (`http.server`) are
+/// skipped; refs like `origin/main` have no extension and are skipped too.
+fn looks_like_path(token: &str) -> bool {
+    if !is_path_candidate(token) {
+        return false;
+    }
+    let explicit = ["/", "./", "../", "~/"]
+        .iter()
+        .any(|prefix| token.starts_with(prefix));
+    if explicit {
+        return true;
+    }
+    let path = Path::new(token);
+    let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+        return false;
+    };
+    if token.contains('/') {
+        !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphabetic())
+    } else {
+        language_for_path(path).is_some()
+            || PLAIN_FILE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+    }
+}
+
+/// Matches `s/a/b/`, `s|a|b|g`, `y/abc/xyz/` and similar sed commands.
+fn is_sed_expression(token: &str) -> bool {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some('s' | 'y'), Some(delim)) if !delim.is_alphanumeric() && !delim.is_whitespace() => {
+            token.matches(delim).count() >= 3
+        }
+        _ => false,
+    }
+}
+
+fn push_unique(list: &mut Vec<String>, value: String) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
 fn truncate_multiline(text: &str, max_chars: usize, max_lines: usize) -> String {
     let mut s: String = text.lines().take(max_lines).collect::<Vec<_>>().join("\n");
     if s.len() > max_chars {
@@ -161,10 +605,65 @@ fn truncate_multiline(text: &str, max_chars: usize, max_lines: usize) -> String
     s
 }
 
-fn detect_repo_root(start: &Path) -> Option<PathBuf> {
+/// Controls how [`detect_repo_root`] walks up from a starting directory.
+#[derive(Debug, Clone)]
+pub(crate) struct RepoRootOptions {
+    /// Entry names whose presence marks a repo root, e.g. `.git` or `Cargo.toml`.
+    pub markers: Vec<String>,
+    /// Maximum number of directories (including the start) to inspect.
+    pub max_depth: usize,
+}
+
+impl Default for RepoRootOptions {
+    fn default() -> Self {
+        Self {
+            markers: vec![".git".to_string(), ".codex".to_string()],
+            max_depth: 64,
+        }
+    }
+}
+
+/// Env var overriding [`RepoRootOptions::markers`], as a comma-separated list.
+const ROOT_MARKERS_ENV_VAR: &str = "CODEX_MEMORY_ROOT_MARKERS";
+/// Env var overriding [`RepoRootOptions::max_depth`].
+const ROOT_MAX_DEPTH_ENV_VAR: &str = "CODEX_MEMORY_ROOT_MAX_DEPTH";
+
+impl RepoRootOptions {
+    /// Defaults overridden by `CODEX_MEMORY_ROOT_MARKERS` and
+    /// `CODEX_MEMORY_ROOT_MAX_DEPTH` when set. Per-repo config cannot be used
+    /// here because it lives under the root being searched for.
+    fn from_env() -> Self {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let mut opts = Self::default();
+        if let Some(markers) = lookup(ROOT_MARKERS_ENV_VAR) {
+            let markers: Vec<String> = markers
+                .split(',')
+                .map(str::trim)
+                .filter(|m| !m.is_empty())
+                .map(str::to_string)
+                .collect();
+            if !markers.is_empty() {
+                opts.markers = markers;
+            }
+        }
+        match lookup(ROOT_MAX_DEPTH_ENV_VAR).map(|v| v.trim().parse::<usize>()) {
+            Some(Ok(depth)) if depth > 0 => opts.max_depth = depth,
+            Some(_) => tracing::debug!("memory: ignoring invalid {ROOT_MAX_DEPTH_ENV_VAR}"),
+            None => {}
+        }
+        opts
+    }
+}
+
+/// Returns the nearest ancestor of `start` containing one of the configured
+/// markers, or `None` if none is found within `max_depth` levels.
+fn detect_repo_root(start: &Path, opts: &RepoRootOptions) -> Option<PathBuf> {
     let mut cur = start.canonicalize().unwrap_or(start.to_path_buf());
-    for _ in 0..64 {
-        if cur.join(".git").exists() || cur.join(".codex").exists() {
+    for _ in 0..opts.max_depth {
+        if opts.markers.iter().any(|m| is_root_marker(&cur.join(m))) {
             return Some(cur);
         }
         if let Some(parent) = cur.parent() {
@@ -176,3 +675,785 @@ fn detect_repo_root(start: &Path) -> Option<PathBuf> {
     None
 }
 
+fn is_root_marker(path: &Path) -> bool {
+    // In worktrees and submodules `.git` is a file holding `gitdir: <path>`
+    // rather than a directory; anything else named `.git` is not a checkout.
+    if path.file_name().is_some_and(|n| n == ".git") && path.is_file() {
+        return std::fs::read_to_string(path)
+            .map(|s| s.trim_start().starts_with("gitdir:"))
+            .unwrap_or(false);
+    }
+    path.exists()
+}
+
+/// Appends `lines` to `path`, each terminated by `\n`. If the file does not
+/// already end in a newline (e.g. after a hand edit) one is inserted first so
+/// the new records never concatenate onto the previous last line.
+fn append_lines(path: &Path, lines: &[String]) -> std::io::Result<()> {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+    let mut buf = String::new();
+    let len = f.metadata()?.len();
+    if len > 0 {
+        let mut last = [0u8; 1];
+        f.seek(SeekFrom::Start(len - 1))?;
+        f.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            buf.push('\n');
+        }
+    }
+    for line in lines {
+        buf.push_str(line);
+        buf.push('\n');
+    }
+    // A single write keeps each batch contiguous with concurrent appenders.
+    f.write_all(buf.as_bytes())
+}
+
+/// Timestamp embedded in rotated event log names.
+const ROTATED_STAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// Whether `name` has the exact `events-<stamp>-<nnn>.jsonl` shape written by
+/// [`MemoryLogger::rotate_if_needed`], so unrelated files such as
+/// `events-backup.jsonl` are never counted or pruned.
+fn is_rotated_event_log_name(name: &str) -> bool {
+    let Some((stamp, counter)) = name
+        .strip_prefix("events-")
+        .and_then(|rest| rest.strip_suffix(".jsonl"))
+        .and_then(|rest| rest.rsplit_once('-'))
+    else {
+        return false;
+    };
+    counter.len() == 3
+        && counter.bytes().all(|b| b.is_ascii_digit())
+        && stamp.len() == "YYYYMMDDTHHMMSS.mmmZ".len()
+        && chrono::NaiveDateTime::parse_from_str(stamp, ROTATED_STAMP_FORMAT).is_ok()
+}
+
+/// Returns rotated event logs in `memory_dir`, oldest first.
+fn rotated_event_logs(memory_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut logs = Vec::new();
+    for entry in std::fs::read_dir(memory_dir)? {
+        let path = entry?.path();
+        let is_rotated = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(is_rotated_event_log_name);
+        if is_rotated {
+            logs.push(path);
+        }
+    }
+    // Timestamps in the names sort chronologically.
+    logs.sort();
+    Ok(logs)
+}
+
+fn is_event_line(line: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(line)
+        .ok()
+        .and_then(|v| v.get("type").and_then(|t| t.as_str()).map(str::to_owned))
+        .is_some_and(|t| EVENT_TYPES.contains(&t.as_str()))
+}
+
+/// Marker written once `memory.jsonl` has been checked for event lines, so
+/// later startups skip re-reading the durable file.
+const EVENTS_MIGRATED_MARKER: &str = ".events-migrated";
+const MIGRATION_LOCK: &str = ".events-migration.lock";
+/// A lock older than this is assumed to belong to a crashed process.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Exclusive lock taken by creating a file; removed on drop.
+struct MigrationLock(PathBuf);
+
+impl MigrationLock {
+    fn try_acquire(path: PathBuf) -> std::io::Result<Option<Self>> {
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Some(Self(path))),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let stale = std::fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|t| t.elapsed().ok())
+                        .is_some_and(|age| age > STALE_LOCK_AGE);
+                    if !stale {
+                        return Ok(None);
+                    }
+                    let _ = std::fs::remove_file(&path);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for MigrationLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// One-time move of event lines out of `memory.jsonl` into `events.jsonl`,
+/// leaving durable items in place. Returns the number of lines moved.
+///
+/// Runs under a lock file so concurrent startups do not both copy the events,
+/// and is abandoned (to be retried next startup) if `memory.jsonl` changes
+/// while the rewrite is prepared. The marker is written before the rewrite is
+/// renamed into place, so a failed rename can leave event lines behind in
+/// `memory.jsonl` but never duplicates them in `events.jsonl`.
+fn migrate_event_lines(memory_dir: &Path) -> std::io::Result<usize> {
+    let marker = memory_dir.join(EVENTS_MIGRATED_MARKER);
+    if marker.exists() {
+        return Ok(0);
+    }
+    if !memory_dir.join("memory.jsonl").exists() {
+        std::fs::File::create(&marker)?;
+        return Ok(0);
+    }
+    let Some(_lock) = MigrationLock::try_acquire(memory_dir.join(MIGRATION_LOCK))? else {
+        return Ok(0);
+    };
+    migrate_event_lines_locked(memory_dir)
+}
+
+/// Body of [`migrate_event_lines`]; the caller must hold the migration lock.
+fn migrate_event_lines_locked(memory_dir: &Path) -> std::io::Result<usize> {
+    // Another process may have finished (or half-finished) the migration
+    // between our marker check and taking the lock.
+    let marker = memory_dir.join(EVENTS_MIGRATED_MARKER);
+    if marker.exists() {
+        return Ok(0);
+    }
+    let memory_file = memory_dir.join("memory.jsonl");
+    let before = match std::fs::metadata(&memory_file) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            std::fs::File::create(&marker)?;
+            return Ok(0);
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut durable = Vec::new();
+    let mut events = Vec::new();
+    for line in BufReader::new(std::fs::File::open(&memory_file)?).lines() {
+        let line = line?;
+        if is_event_line(&line) {
+            events.push(line);
+        } else if !line.trim().is_empty() {
+            durable.push(line);
+        }
+    }
+    if events.is_empty() {
+        // Nothing to move: leave `memory.jsonl` untouched.
+        std::fs::File::create(&marker)?;
+        return Ok(0);
+    }
+
+    // Rewrite via a per-process temp file so a crash never leaves
+    // `memory.jsonl` truncated and concurrent migrations cannot collide.
+    let tmp = memory_dir.join(format!("memory.jsonl.{}.tmp", std::process::id()));
+    {
+        let mut f = std::fs::File::create(&tmp)?;
+        for line in &durable {
+            writeln!(f, "{line}")?;
+        }
+        f.sync_all()?;
+    }
+    // Another writer appended while we were reading: keep its line and retry
+    // on a later startup instead of clobbering it.
+    let after = std::fs::metadata(&memory_file)?;
+    if after.len() != before.len() || after.modified().ok() != before.modified().ok() {
+        let _ = std::fs::remove_file(&tmp);
+        return Ok(0);
+    }
+
+    let result = append_lines(&memory_dir.join("events.jsonl"), &events)
+        .and_then(|()| std::fs::File::create(&marker).map(drop))
+        .and_then(|()| std::fs::rename(&tmp, &memory_file));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result.map(|()| events.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn repo() -> TempDir {
+        let dir = TempDir::new().expect("tempdir");
+        std::fs::create_dir(dir.path().join(".git")).expect("create .git");
+        dir
+    }
+
+    fn read_lines(path: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(|l| serde_json::from_str(l).expect("valid json"))
+            .collect()
+    }
+
+    #[test]
+    fn repo_root_detects_worktree_git_file() {
+        let dir = TempDir::new().expect("tempdir");
+        let nested = dir.path().join("src").join("bin");
+        std::fs::create_dir_all(&nested).expect("create nested");
+        std::fs::write(
+            dir.path().join(".git"),
+            "gitdir: /elsewhere/.git/worktrees/wt\n",
+        )
+        .expect("write .git file");
+
+        let root = detect_repo_root(&nested, &RepoRootOptions::default());
+        assert_eq!(root, Some(dir.path().canonicalize().expect("canonicalize")));
+    }
+
+    #[test]
+    fn repo_root_ignores_git_file_without_gitdir() {
+        let dir = TempDir::new().expect("tempdir");
+        std::fs::write(dir.path().join(".git"), "not a checkout").expect("write .git file");
+
+        let opts = RepoRootOptions {
+            max_depth: 1,
+            ..RepoRootOptions::default()
+        };
+        assert_eq!(detect_repo_root(dir.path(), &opts), None);
+    }
+
+    #[test]
+    fn repo_root_detects_codex_only_project() {
+        let dir = TempDir::new().expect("tempdir");
+        std::fs::create_dir(dir.path().join(".codex")).expect("create .codex");
+        let nested = dir.path().join("docs");
+        std::fs::create_dir(&nested).expect("create nested");
+
+        let root = detect_repo_root(&nested, &RepoRootOptions::default());
+        assert_eq!(root, Some(dir.path().canonicalize().expect("canonicalize")));
+    }
+
+    #[test]
+    fn repo_root_honors_custom_markers_and_depth() {
+        let dir = TempDir::new().expect("tempdir");
+        std::fs::write(dir.path().join("Cargo.toml"), "").expect("write Cargo.toml");
+        let nested = dir.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).expect("create nested");
+
+        let opts = RepoRootOptions {
+            markers: vec!["Cargo.toml".to_string()],
+            max_depth: 3,
+        };
+        let root = detect_repo_root(&nested, &opts);
+        assert_eq!(root, Some(dir.path().canonicalize().expect("canonicalize")));
+
+        let shallow = RepoRootOptions {
+            max_depth: 2,
+            ..opts
+        };
+        assert_eq!(detect_repo_root(&nested, &shallow), None);
+    }
+
+    #[test]
+    fn repo_root_options_read_env_overrides() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| (*v).to_string())
+            }
+        };
+
+        let opts = RepoRootOptions::from_lookup(env(&[
+            (ROOT_MARKERS_ENV_VAR, "Cargo.toml, .hg ,"),
+            (ROOT_MAX_DEPTH_ENV_VAR, "3"),
+        ]));
+        assert_eq!(
+            opts.markers,
+            vec!["Cargo.toml".to_string(), ".hg".to_string()]
+        );
+        assert_eq!(opts.max_depth, 3);
+
+        let fallback = RepoRootOptions::from_lookup(env(&[
+            (ROOT_MARKERS_ENV_VAR, " , "),
+            (ROOT_MAX_DEPTH_ENV_VAR, "deep"),
+        ]));
+        assert_eq!(fallback.markers, RepoRootOptions::default().markers);
+        assert_eq!(fallback.max_depth, RepoRootOptions::default().max_depth);
+
+        let dir = TempDir::new().expect("tempdir");
+        std::fs::write(dir.path().join("Cargo.toml"), "").expect("write Cargo.toml");
+        let nested = dir.path().join("crate").join("src");
+        std::fs::create_dir_all(&nested).expect("create nested");
+        let root = detect_repo_root(&nested, &opts);
+        assert_eq!(root, Some(dir.path().canonicalize().expect("canonicalize")));
+    }
+
+    #[test]
+    fn ignore_rules_drop_matching_entries() {
+        let dir = repo();
+        let memory_dir = dir.path().join(".codex").join("memory");
+        std::fs::create_dir_all(&memory_dir).expect("create memory dir");
+        std::fs::write(
+            memory_dir.join("ignore"),
+            "# private work\nre:TODO-private\nsecrets/*\n",
+        )
+        .expect("write ignore");
+
+        let logger = MemoryLogger::new(dir.path().to_path_buf());
+        let d = Duration::from_millis(1);
+        let path = |p: &str| dir.path().join(p).to_string_lossy().to_string();
+        logger.log_exec(&["echo".into(), "TODO-private".into()], 0, d, "");
+        logger.log_exec(
+            &["cat".into(), "notes".into()],
+            0,
+            d,
+            "TODO-private: rotate keys",
+        );
+        logger.log_tool_call(ToolInvocation {
+            server: "fs".to_string(),
+            tool: "read".to_string(),
+            arguments: None,
+            duration: d,
+            success: true,
+            result: Some(serde_json::json!({ "text": "TODO-private" })),
+        });
+        logger.log_patch_apply(true, true, d, "", "", &[path("secrets/key.pem")]);
+        let script = |s: &str| vec!["bash".to_string(), "-lc".to_string(), s.to_string()];
+        logger.log_exec(&script("cat secrets/prod_token"), 0, d, "");
+        logger.log_exec(
+            &script(&format!("cat {}", path("secrets/prod_token"))),
+            0,
+            d,
+            "",
+        );
+        logger.log_exec(&["echo".into(), "hello".into()], 0, d, "");
+        logger.log_patch_apply(true, true, d, "", "", &[path("src/lib.rs")]);
+
+        let events = read_lines(&memory_dir.join("events.jsonl"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["content"], "echo hello");
+        assert_eq!(events[1]["hints"]["files"][0], "src/lib.rs");
+    }
+
+    #[test]
+    fn command_hints_extract_program_and_files() {
+        let hints = RelevanceHints::from_command(&[
+            "cargo".to_string(),
+            "test".to_string(),
+            "src/foo.rs".to_string(),
+        ]);
+        assert_eq!(
+            hints,
+            RelevanceHints {
+                files: vec!["src/foo.rs".to_string()],
+                commands: vec!["cargo".to_string()],
+                languages: vec!["rust".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn command_hints_unwrap_shell_scripts() {
+        let hints = RelevanceHints::from_command(&[
+            "bash".to_string(),
+            "-lc".to_string(),
+            "rg --files -g '*.rs' src && /usr/bin/git diff README.md".to_string(),
+        ]);
+        assert_eq!(hints.commands, vec!["rg".to_string(), "git".to_string()]);
+        assert_eq!(hints.files, vec!["README.md".to_string()]);
+    }
+
+    #[test]
+    fn language_for_path_maps_common_extensions() {
+        let cases = [
+            ("src/main.rs", Some("rust")),
+            ("tools/gen.py", Some("python")),
+            ("web/app.tsx", Some("typescript")),
+            ("index.MJS", Some("javascript")),
+            ("Makefile", None),
+            ("notes.unknown", None),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(language_for_path(Path::new(path)), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn command_hints_populate_languages() {
+        let hints = RelevanceHints::from_command(&[
+            "python".to_string(),
+            "tools/gen.py".to_string(),
+            "src/lib.rs".to_string(),
+            "src/main.rs".to_string(),
+        ]);
+        assert_eq!(
+            hints.languages,
+            vec!["python".to_string(), "rust".to_string()]
+        );
+    }
+
+    #[test]
+    fn command_hints_skip_refs_and_sed_expressions() {
+        let checkout = RelevanceHints::from_command(&[
+            "git".to_string(),
+            "checkout".to_string(),
+            "origin/main".to_string(),
+        ]);
+        assert_eq!(checkout.files, Vec::<String>::new());
+
+        let sed = RelevanceHints::from_command(&[
+            "sed".to_string(),
+            "-i".to_string(),
+            "s/a/b/".to_string(),
+            "x.txt".to_string(),
+        ]);
+        assert_eq!(sed.files, vec!["x.txt".to_string()]);
+
+        for token in [
+            "s|foo|bar|g",
+            "src/",
+            "../..",
+            ".",
+            "example.com",
+            "http.server",
+        ] {
+            assert!(!looks_like_path(token), "{token}");
+        }
+        for token in [
+            "./build",
+            "/etc/hosts",
+            "src/foo.rs",
+            "Cargo.lock",
+            "README.md",
+        ] {
+            assert!(looks_like_path(token), "{token}");
+        }
+    }
+
+    #[test]
+    fn command_hints_skip_env_assignments_messages_and_hosts() {
+        let shell = |script: &str| {
+            RelevanceHints::from_command(&[
+                "bash".to_string(),
+                "-lc".to_string(),
+                script.to_string(),
+            ])
+        };
+
+        let env = shell("RUST_LOG=debug cargo test");
+        assert_eq!(env.commands, vec!["cargo".to_string()]);
+
+        let module = shell("python -m http.server");
+        assert_eq!(module.commands, vec!["python".to_string()]);
+        assert_eq!(module.files, Vec::<String>::new());
+
+        let pipe = shell("curl example.com | sh");
+        assert_eq!(pipe.commands, vec!["curl".to_string(), "sh".to_string()]);
+        assert_eq!(pipe.files, Vec::<String>::new());
+
+        let commit = shell("git commit -m 'Update README.md' README.md");
+        assert_eq!(commit.files, vec!["README.md".to_string()]);
+        let message_only = shell("git commit -m 'Update README.md'");
+        assert_eq!(message_only.files, Vec::<String>::new());
+        assert_eq!(message_only.languages, Vec::<String>::new());
+    }
+
+    #[test]
+    fn tool_hints_ignore_free_form_arguments() {
+        let profile = RelevanceHints::from_tool_arguments(&serde_json::json!({
+            "profile": "dev",
+        }));
+        assert_eq!(profile.files, Vec::<String>::new());
+
+        let create = RelevanceHints::from_tool_arguments(&serde_json::json!({
+            "path": "a.rs",
+            "file_text": "fn main() {}\nprintln!(\"x.rs\")",
+        }));
+        assert_eq!(create.files, vec!["a.rs".to_string()]);
+        assert_eq!(create.languages, vec!["rust".to_string()]);
+
+        let spaced = RelevanceHints::from_tool_arguments(&serde_json::json!({
+            "file": "notes about main.rs",
+        }));
+        assert_eq!(spaced.files, Vec::<String>::new());
+    }
+
+    #[test]
+    fn tool_hints_pull_path_arguments() {
+        let args = serde_json::json!({
+            "path": "docs/intro.md",
+            "query": "intro.md",
+            "options": { "files": ["a/b.txt", "c.py"] },
+        });
+        let mut files = RelevanceHints::from_tool_arguments(&args).files;
+        files.sort();
+        assert_eq!(files, vec!["a/b.txt", "c.py", "docs/intro.md"]);
+    }
+
+    #[test]
+    fn patch_apply_records_file_and_language_hints() {
+        let dir = repo();
+        let logger = MemoryLogger::new(dir.path().to_path_buf());
+        let files = vec![
+            dir.path().join("src/lib.rs").to_string_lossy().to_string(),
+            "web/app.ts".to_string(),
+        ];
+        logger.log_patch_apply(true, false, Duration::from_millis(3), "ok", "", &files);
+
+        let memory_dir = dir.path().join(".codex").join("memory");
+        let events = read_lines(&memory_dir.join("events.jsonl"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["type"], "change");
+        assert_eq!(
+            events[0]["files"],
+            serde_json::json!(["src/lib.rs", "web/app.ts"])
+        );
+        assert_eq!(
+            events[0]["hints"],
+            serde_json::json!({
+                "files": ["src/lib.rs", "web/app.ts"],
+                "commands": [],
+                "languages": ["rust", "typescript"],
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn patch_apply_hints_are_relative_through_symlinked_cwd() {
+        let dir = repo();
+        let links = TempDir::new().expect("tempdir");
+        let link = links.path().join("repo-link");
+        std::os::unix::fs::symlink(dir.path(), &link).expect("symlink");
+
+        let logger = MemoryLogger::new(link.clone());
+        let files = vec![link.join("src/main.rs").to_string_lossy().to_string()];
+        logger.log_patch_apply(true, false, Duration::from_millis(3), "ok", "", &files);
+
+        let events = read_lines(&dir.path().join(".codex/memory/events.jsonl"));
+        assert_eq!(events[0]["files"], serde_json::json!(["src/main.rs"]));
+        assert_eq!(
+            events[0]["hints"]["files"],
+            serde_json::json!(["src/main.rs"])
+        );
+    }
+
+    #[test]
+    fn events_are_written_to_events_file() {
+        let dir = repo();
+        let logger = MemoryLogger::new(dir.path().to_path_buf());
+        logger.log_exec(&["ls".to_string()], 0, Duration::from_millis(5), "ok");
+
+        let memory_dir = dir.path().join(".codex").join("memory");
+        let events = read_lines(&memory_dir.join("events.jsonl"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["type"], "exec");
+        assert!(!memory_dir.join("memory.jsonl").exists());
+    }
+
+    #[test]
+    fn rotated_log_names_match_only_the_written_shape() {
+        let written = format!(
+            "events-{}-000.jsonl",
+            Utc::now().format(ROTATED_STAMP_FORMAT)
+        );
+        assert!(is_rotated_event_log_name(&written));
+        for name in [
+            "events-backup.jsonl",
+            "events-20261016T120000.123Z.jsonl",
+            "events-20261016T120000.123Z-7.jsonl",
+            "events-2026-10-16-000.jsonl",
+            "events.jsonl",
+        ] {
+            assert!(!is_rotated_event_log_name(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn memory_config_loads_toml_with_defaults() {
+        let dir = TempDir::new().expect("tempdir");
+        let config = dir.path().join("config.toml");
+        assert_eq!(MemoryConfig::load(&config), MemoryConfig::default());
+
+        std::fs::write(&config, "events_enabled = false\n").expect("write config");
+        assert_eq!(
+            MemoryConfig::load(&config),
+            MemoryConfig {
+                events_enabled: false,
+                ..MemoryConfig::default()
+            }
+        );
+
+        std::fs::write(&config, "events_max_bytes = 4096\nevents_keep = 1\n")
+            .expect("write config");
+        assert_eq!(
+            MemoryConfig::load(&config),
+            MemoryConfig {
+                events_max_bytes: 4096,
+                events_keep: 1,
+                ..MemoryConfig::default()
+            }
+        );
+
+        std::fs::write(&config, "events_enabled = \"maybe\"").expect("write config");
+        assert_eq!(MemoryConfig::load(&config), MemoryConfig::default());
+    }
+
+    #[test]
+    fn disabled_event_logging_writes_nothing() {
+        let dir = repo();
+        let memory_dir = dir.path().join(".codex").join("memory");
+        std::fs::create_dir_all(&memory_dir).expect("create memory dir");
+        std::fs::write(memory_dir.join("config.toml"), "events_enabled = false\n")
+            .expect("write config");
+
+        let logger = MemoryLogger::new(dir.path().to_path_buf());
+        logger.log_exec(&["ls".to_string()], 0, Duration::from_millis(1), "");
+
+        assert!(!memory_dir.join("events.jsonl").exists());
+    }
+
+    #[test]
+    fn event_log_rotates_and_keeps_newest_files() {
+        let dir = repo();
+        let memory_dir = dir.path().join(".codex").join("memory");
+        std::fs::create_dir_all(&memory_dir).expect("create memory dir");
+        std::fs::write(
+            memory_dir.join("config.toml"),
+            "events_max_bytes = 200\nevents_keep = 2\n",
+        )
+        .expect("write config.toml");
+        let logger = MemoryLogger::new(dir.path().to_path_buf());
+        // A user file with a similar name must not count as a rotation.
+        let backup = memory_dir.join("events-backup.jsonl");
+        std::fs::write(&backup, "{}\n").expect("write backup");
+        for i in 0..20 {
+            logger.log_exec(&[format!("cmd{i}")], 0, Duration::from_millis(1), "");
+        }
+
+        let rotated = rotated_event_logs(&memory_dir).expect("list rotated logs");
+        assert_eq!(rotated.len(), 2);
+        assert!(!rotated.contains(&backup));
+        assert!(backup.exists());
+        let current = std::fs::metadata(memory_dir.join("events.jsonl")).expect("events.jsonl");
+        assert!(current.len() < 2 * 200);
+        // The newest entry is always in the live file.
+        let events = read_lines(&memory_dir.join("events.jsonl"));
+        assert_eq!(
+            events.last().map(|e| e["content"].clone()),
+            Some("cmd19".into())
+        );
+    }
+
+    #[test]
+    fn append_after_missing_trailing_newline_keeps_lines_separate() {
+        let dir = repo();
+        let memory_dir = dir.path().join(".codex").join("memory");
+        std::fs::create_dir_all(&memory_dir).expect("create memory dir");
+        let events_file = memory_dir.join("events.jsonl");
+        std::fs::write(&events_file, r#"{"id":"e0","type":"exec","content":"ls"}"#)
+            .expect("seed events.jsonl");
+
+        let logger = MemoryLogger::new(dir.path().to_path_buf());
+        logger.log_exec(&["pwd".to_string()], 0, Duration::from_millis(1), "");
+
+        let raw = std::fs::read_to_string(&events_file).expect("read events.jsonl");
+        assert!(raw.ends_with('\n'));
+        let events = read_lines(&events_file);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["id"], "e0");
+        assert_eq!(events[1]["content"], "pwd");
+    }
+
+    #[test]
+    fn existing_event_lines_are_migrated_out_of_memory_file() {
+        let dir = repo();
+        let memory_dir = dir.path().join(".codex").join("memory");
+        std::fs::create_dir_all(&memory_dir).expect("create memory dir");
+        let durable = r#"{"id":"p1","kind":"pref","content":"use rg"}"#;
+        let event = r#"{"id":"e1","type":"exec","content":"ls"}"#;
+        std::fs::write(
+            memory_dir.join("memory.jsonl"),
+            format!("{event}\n{durable}\n"),
+        )
+        .expect("seed memory.jsonl");
+
+        let _logger = MemoryLogger::new(dir.path().to_path_buf());
+
+        let remaining = read_lines(&memory_dir.join("memory.jsonl"));
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0]["id"], "p1");
+        let events = read_lines(&memory_dir.join("events.jsonl"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["id"], "e1");
+        assert!(memory_dir.join(EVENTS_MIGRATED_MARKER).exists());
+        assert!(!memory_dir.join(MIGRATION_LOCK).exists());
+
+        // Later startups do not migrate (or duplicate) again.
+        let _logger = MemoryLogger::new(dir.path().to_path_buf());
+        assert_eq!(read_lines(&memory_dir.join("events.jsonl")).len(), 1);
+    }
+
+    #[test]
+    fn migration_without_event_lines_leaves_memory_file_untouched() {
+        let dir = repo();
+        let memory_dir = dir.path().join(".codex").join("memory");
+        std::fs::create_dir_all(&memory_dir).expect("create memory dir");
+        let memory_file = memory_dir.join("memory.jsonl");
+        // No trailing newline: a rewrite would add one.
+        let durable = r#"{"id":"p1","kind":"pref","content":"use rg"}"#;
+        std::fs::write(&memory_file, durable).expect("seed memory.jsonl");
+
+        assert_eq!(migrate_event_lines(&memory_dir).expect("migrate"), 0);
+
+        assert_eq!(
+            std::fs::read_to_string(&memory_file).expect("read"),
+            durable
+        );
+        assert!(memory_dir.join(EVENTS_MIGRATED_MARKER).exists());
+        assert!(!memory_dir.join("events.jsonl").exists());
+    }
+
+    #[test]
+    fn migration_skips_while_another_process_holds_the_lock() {
+        let dir = repo();
+        let memory_dir = dir.path().join(".codex").join("memory");
+        std::fs::create_dir_all(&memory_dir).expect("create memory dir");
+        let event = r#"{"id":"e1","type":"exec","content":"ls"}"#;
+        std::fs::write(memory_dir.join("memory.jsonl"), format!("{event}\n"))
+            .expect("seed memory.jsonl");
+        std::fs::write(memory_dir.join(MIGRATION_LOCK), "").expect("hold lock");
+
+        assert_eq!(migrate_event_lines(&memory_dir).expect("migrate"), 0);
+
+        assert_eq!(read_lines(&memory_dir.join("memory.jsonl")).len(), 1);
+        assert!(!memory_dir.join("events.jsonl").exists());
+        assert!(!memory_dir.join(EVENTS_MIGRATED_MARKER).exists());
+    }
+
+    #[test]
+    fn migration_rechecks_marker_after_taking_the_lock() {
+        // Another process migrated and wrote the marker, but its rename of
+        // `memory.jsonl` failed, so the event line is still there.
+        let dir = repo();
+        let memory_dir = dir.path().join(".codex").join("memory");
+        std::fs::create_dir_all(&memory_dir).expect("create memory dir");
+        let event = r#"{"id":"e1","type":"exec","content":"ls"}"#;
+        std::fs::write(memory_dir.join("memory.jsonl"), format!("{event}\n"))
+            .expect("seed memory.jsonl");
+        std::fs::write(memory_dir.join("events.jsonl"), format!("{event}\n"))
+            .expect("seed events.jsonl");
+        std::fs::File::create(memory_dir.join(EVENTS_MIGRATED_MARKER)).expect("write marker");
+
+        assert_eq!(migrate_event_lines_locked(&memory_dir).expect("migrate"), 0);
+
+        assert_eq!(read_lines(&memory_dir.join("events.jsonl")).len(), 1);
+        assert_eq!(read_lines(&memory_dir.join("memory.jsonl")).len(), 1);
+    }
+}